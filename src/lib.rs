@@ -1,59 +1,136 @@
-/// A generic-error that contains the serialized error-kind, description, the position (file, line)
-/// and an optional sub-error
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::{format, rc::Rc, string::{String, ToString}};
+#[cfg(not(feature = "std"))]
+use alloc::{format, rc::Rc, string::{String, ToString}};
+
+
+/// A zero-size compat shim for `std::backtrace::Backtrace` that is used in place of the real type
+/// when the `backtrace` feature (which requires `std`) is disabled
+///
+/// This keeps the `backtrace` field on `Error<T>`/`WrappedError` and all code paths that touch it
+/// compiling unconditionally, regardless of whether the feature is enabled
+#[cfg(not(all(feature = "std", feature = "backtrace")))]
+#[derive(Clone)]
+pub struct Backtrace;
+#[cfg(not(all(feature = "std", feature = "backtrace")))]
+impl fmt::Debug for Backtrace {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "not available")
+	}
+}
+#[cfg(not(all(feature = "std", feature = "backtrace")))]
+impl fmt::Display for Backtrace {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "not available")
+	}
+}
+
+/// The real backtrace type, captured at error-creation time if the `backtrace` feature is enabled
+#[cfg(all(feature = "std", feature = "backtrace"))]
+pub use std::backtrace::Backtrace;
+
+/// Captures a `Backtrace` at the call site if the `backtrace` feature is enabled, or returns
+/// `None` otherwise
+#[cfg(all(feature = "std", feature = "backtrace"))]
+fn capture_backtrace() -> Option<Rc<Backtrace>> {
+	Some(Rc::new(Backtrace::capture()))
+}
+#[cfg(not(all(feature = "std", feature = "backtrace")))]
+fn capture_backtrace() -> Option<Rc<Backtrace>> {
+	None
+}
+
+
+/// A generic-error that contains the serialized error-kind, description, the position (file, line),
+/// an optional backtrace and an optional sub-error
 #[derive(Debug, Clone)]
 pub struct WrappedError {
 	pub kind_repr: String,
 	pub description: String,
 	pub file: &'static str,
 	pub line: u32,
-	pub sub_error: Option<std::rc::Rc<WrappedError>>
+	pub backtrace: Option<Rc<Backtrace>>,
+	pub sub_error: Option<Rc<WrappedError>>
 }
-impl<T: std::fmt::Debug + Send> From<Error<T>> for WrappedError {
+impl<T: fmt::Debug + Send> From<Error<T>> for WrappedError {
 	fn from(error: Error<T>) -> Self {
 		WrappedError {
 			kind_repr: format!("{:?}", error.kind), description: error.description,
 			file: error.file, line: error.line,
+			backtrace: error.backtrace,
 			sub_error: error.sub_error
 		}
 	}
 }
-impl std::fmt::Display for WrappedError {
-	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl fmt::Display for WrappedError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		write!(f, "{}: {} (at {}:{})", self.kind_repr, self.description, self.file, self.line)?;
-		if let Some(ref sub_error) = self.sub_error { write!(f, "\n  - {}", sub_error.to_string())?; }
+		if let Some(ref backtrace) = self.backtrace { write!(f, "\n{}", backtrace)?; }
+		if let Some(ref sub_error) = self.sub_error { write!(f, "\n  - {}", sub_error)?; }
 		Ok(())
 	}
 }
+#[cfg(feature = "std")]
 impl std::error::Error for WrappedError {
 	fn description(&self) -> &str { self.description.as_str() }
-	
-	fn cause(&self) -> Option<&std::error::Error> {
-		self.sub_error.as_ref().and_then(|e| {
-			let sub_error: &std::error::Error = e.as_ref();
-			Some(sub_error)
-		})
+
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		self.sub_error.as_ref().map(|e| e.as_ref() as &(dyn std::error::Error + 'static))
 	}
 }
 unsafe impl Send for WrappedError {}
+#[cfg(feature = "std")]
+impl WrappedError {
+	/// Creates an iterator that walks the chain of causes, starting at `self` and yielding each
+	/// sub-error in turn until the chain is exhausted
+	pub fn iter(&self) -> Causes<'_> {
+		Causes{ current: Some(self) }
+	}
+}
+
+
+/// An iterator over a chain of causes, yielding each link from outermost to innermost
+///
+/// Created by `Error::iter()`/`WrappedError::iter()`
+#[cfg(feature = "std")]
+pub struct Causes<'a> {
+	current: Option<&'a (dyn std::error::Error + 'static)>
+}
+#[cfg(feature = "std")]
+impl<'a> Iterator for Causes<'a> {
+	type Item = &'a (dyn std::error::Error + 'static);
+	fn next(&mut self) -> Option<Self::Item> {
+		let current = self.current.take()?;
+		self.current = current.source();
+		Some(current)
+	}
+}
 
 
-/// A typed-error that contains the error-kind, description, the position (file, line) and an
-/// optional sub-error
+/// A typed-error that contains the error-kind, description, the position (file, line), an
+/// optional backtrace and an optional sub-error
 #[derive(Debug)]
-pub struct Error<T: std::fmt::Debug + Send> {
+pub struct Error<T: fmt::Debug + Send> {
 	pub kind: T,
 	pub description: String,
 	pub file: &'static str,
 	pub line: u32,
-	pub sub_error: Option<std::rc::Rc<WrappedError>>
+	pub backtrace: Option<Rc<Backtrace>>,
+	pub sub_error: Option<Rc<WrappedError>>
 }
-impl<T: std::fmt::Debug + Send> Error<T> {
+impl<T: fmt::Debug + Send> Error<T> {
 	/// Creates a new error with an explicit description
 	///
 	/// _Note: This function is not intended for direct use; take a look at the `new_err!()`-macro
 	/// instead_
 	pub fn with_kind_desc<S: ToString>(kind: T, description: S, file: &'static str, line: u32) -> Self {
-		Error{ kind, description: description.to_string(), file, line, sub_error: None }
+		Error{ kind, description: description.to_string(), file, line, backtrace: capture_backtrace(), sub_error: None }
 	}
 	/// Creates a new error
 	///
@@ -63,13 +140,33 @@ impl<T: std::fmt::Debug + Send> Error<T> {
 		let description = format!("{:?}", kind);
 		Error::with_kind_desc(kind, description, file, line)
 	}
-	
+
+	/// Creates a new error with an explicit description, capturing the caller's source position
+	/// automatically
+	///
+	/// Unlike `with_kind_desc()`, this doesn't need `file!()`/`line!()` threaded in by hand, so it
+	/// can be called ergonomically from plain functions, `From`-impls or closures
+	#[track_caller]
+	pub fn new_with_desc<S: ToString>(kind: T, description: S) -> Self {
+		let location = core::panic::Location::caller();
+		Error::with_kind_desc(kind, description, location.file(), location.line())
+	}
+	/// Creates a new error, capturing the caller's source position automatically
+	///
+	/// Unlike `with_kind()`, this doesn't need `file!()`/`line!()` threaded in by hand, so it can
+	/// be called ergonomically from plain functions, `From`-impls or closures
+	#[track_caller]
+	pub fn new(kind: T) -> Self {
+		let location = core::panic::Location::caller();
+		Error::with_kind(kind, location.file(), location.line())
+	}
+
 	/// Creates a new error with an explicit description and a sub-error
 	///
 	/// _Note: This function is not intended for direct use; take a look at the `rethrow_err!()`-
 	/// macro instead_
 	pub fn propagate_with_kind_desc<S: ToString>(kind: T, description: S, sub_error: WrappedError, file: &'static str, line: u32) -> Self {
-		Error{ kind, description: description.to_string(), file, line, sub_error: Some(std::rc::Rc::new(sub_error)) }
+		Error{ kind, description: description.to_string(), file, line, backtrace: capture_backtrace(), sub_error: Some(Rc::new(sub_error)) }
 	}
 	/// Creates a new error with a sub-error
 	///
@@ -79,7 +176,7 @@ impl<T: std::fmt::Debug + Send> Error<T> {
 		let description = format!("{:?}", kind);
 		Error::propagate_with_kind_desc(kind, description, sub_error, file, line)
 	}
-	
+
 	/// Creates a new error with the same kind and description as in the sub-error
 	///
 	/// _Note: This function is not intended for direct use; take a look at the `rethrow_err!()`-
@@ -88,17 +185,31 @@ impl<T: std::fmt::Debug + Send> Error<T> {
 		Error::propagate_with_kind_desc(sub_error.kind.clone(), sub_error.description.clone(), sub_error.into(), file, line)
 	}
 }
-impl<T: std::fmt::Debug + Send> std::fmt::Display for Error<T> {
-	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl<T: fmt::Debug + Send> fmt::Display for Error<T> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		write!(f, "{:?}: {} (at {}:{})", self.kind, self.description, self.file, self.line)?;
-		if let Some(ref sub_error) = self.sub_error { write!(f, "\n  - {}", sub_error.to_string())?; }
+		if let Some(ref backtrace) = self.backtrace { write!(f, "\n{}", backtrace)?; }
+		if let Some(ref sub_error) = self.sub_error { write!(f, "\n  - {}", sub_error)?; }
 		Ok(())
 	}
 }
-impl<T: std::fmt::Debug + Send> std::error::Error for Error<T> {
+#[cfg(feature = "std")]
+impl<T: fmt::Debug + Send> std::error::Error for Error<T> {
 	fn description(&self) -> &str { self.description.as_str() }
+
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		self.sub_error.as_ref().map(|e| e.as_ref() as &(dyn std::error::Error + 'static))
+	}
+}
+unsafe impl<T: fmt::Debug + Send> Send for Error<T> {}
+#[cfg(feature = "std")]
+impl<T: fmt::Debug + Send + 'static> Error<T> {
+	/// Creates an iterator that walks the chain of causes, starting at `self` and yielding each
+	/// sub-error in turn until the chain is exhausted
+	pub fn iter(&self) -> Causes<'_> {
+		Causes{ current: Some(self) }
+	}
 }
-unsafe impl<T: std::fmt::Debug + Send> Send for Error<T> {}
 
 
 /// Creates a new error
@@ -107,8 +218,8 @@ unsafe impl<T: std::fmt::Debug + Send> Send for Error<T> {}
 /// `new_err!(kind, description)` to provide an explicit description
 #[macro_export]
 macro_rules! new_err {
-	($kind:expr, $description:expr) => ($crate::Error::with_kind_desc($kind, $description, file!(), line!()));
-	($kind:expr) => ($crate::Error::with_kind($kind, file!(), line!()));
+	($kind:expr, $description:expr) => ($crate::Error::new_with_desc($kind, $description));
+	($kind:expr) => ($crate::Error::new($kind));
 }
 
 /// Creates a new error containing the underlaying error
@@ -202,15 +313,51 @@ macro_rules! try_err_from {
 	});
 }
 
+/// Declares an error-kind enum together with `From`-implementations that convert foreign error
+/// types into it
+///
+/// Use
+/// ```ignore
+/// define_error!(MyErrorKind {
+///     InvalidInput,
+///     Io
+/// }, std::io::Error => Io, std::num::ParseIntError => InvalidInput);
+/// ```
+/// to declare `enum MyErrorKind { InvalidInput, Io }` together with `From<std::io::Error>` and
+/// `From<std::num::ParseIntError>` implementations for `MyErrorKind` that select the mapped
+/// variant. Rust's orphan rules forbid implementing `From<ForeignType>` directly for the foreign
+/// `Error<MyErrorKind>`, so the conversion targets the (local) kind enum instead — which is
+/// exactly what `new_err_from!`/`try_err_from!` need to turn a foreign error into an `Error<T>` in
+/// one call, without hand-writing one `From`-impl per source type
+#[macro_export]
+macro_rules! define_error {
+	($name:ident { $($variant:ident),+ $(,)? }, $($source:ty => $target:ident),+ $(,)?) => {
+		#[derive(Debug, Clone, PartialEq, Eq)]
+		pub enum $name {
+			$($variant),+
+		}
+
+		$(
+			impl From<$source> for $name {
+				fn from(_source: $source) -> Self {
+					$name::$target
+				}
+			}
+		)+
+	};
+}
+
 /// Runs `$code` and returns either the unwrapped result or binds the error to `$err` and executes
 /// `$or` (or can then access the error using the identifier passed as `$err`)
 ///
 /// Example:
 /// ```
+/// # use etrace::ok_or;
+/// # let result: Result<u8, &str> = Ok(7);
 /// // This code either prints the error and exits (if error) or prints the result (if ok)
 /// let unwrapped = ok_or!(result, example_error_identifier, {
-/// 	eprintln!("Fatal error: \"{}\"", example_error_identifier);
-/// 	std::process::exit(1);
+///     eprintln!("Fatal error: \"{}\"", example_error_identifier);
+///     std::process::exit(1);
 /// });
 /// println!("Result: \"{}\"", unwrapped);
 /// ```
@@ -233,4 +380,145 @@ macro_rules! some_or {
 		Some(result) => result,
 		None => $or
 	});
-}
\ No newline at end of file
+}
+
+/// Asserts that two errors are equal by comparing the rendered form of their kind/description
+/// chain
+///
+/// `Error<T>` doesn't implement `PartialEq` (it carries `file`/`line`/`Rc` fields that differ per
+/// call site even for "the same" error), so both sides are converted into a `WrappedError` and
+/// rendered into a comparable string that walks the `sub_error` chain but leaves out the
+/// per-call-site `file`/`line` position
+///
+/// Use `assert_error_eq!(left, right)` or `assert_error_eq!(left, right, "message")`
+#[macro_export]
+macro_rules! assert_error_eq {
+	($left:expr, $right:expr, $($msg:tt)+) => {
+		{
+			fn render(error: &$crate::WrappedError) -> String {
+				match error.sub_error {
+					Some(ref sub_error) => format!("{}: {}\n  - {}", error.kind_repr, error.description, render(sub_error)),
+					None => format!("{}: {}", error.kind_repr, error.description)
+				}
+			}
+			let left: $crate::WrappedError = $left.into();
+			let right: $crate::WrappedError = $right.into();
+			assert_eq!(render(&left), render(&right), $($msg)+);
+		}
+	};
+	($left:expr, $right:expr) => {
+		assert_error_eq!($left, $right, "errors are not equal")
+	};
+}
+
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+	use super::*;
+
+	#[derive(Debug, Clone)]
+	enum Kind { A, B }
+
+	#[test]
+	fn assert_error_eq_passes_for_matching_kind_and_description() {
+		let left: Error<Kind> = new_err!(Kind::A, "boom");
+		let right: Error<Kind> = new_err!(Kind::A, "boom");
+		assert_error_eq!(left, right);
+
+		let left: Error<Kind> = new_err!(Kind::A, "boom");
+		let right: Error<Kind> = new_err!(Kind::A, "boom");
+		assert_error_eq!(left, right, "errors should render the same, got {:?}", "mismatch");
+	}
+
+	#[test]
+	#[should_panic]
+	fn assert_error_eq_fails_for_different_description() {
+		let left: Error<Kind> = new_err!(Kind::A, "boom");
+		let right: Error<Kind> = new_err!(Kind::A, "bang");
+		assert_error_eq!(left, right);
+	}
+
+	#[test]
+	fn iter_and_source_walk_the_cause_chain_outermost_to_innermost() {
+		fn inner() -> Result<(), Error<Kind>> {
+			throw_err!(Kind::A, "inner failed")
+		}
+		fn outer() -> Result<(), Error<Kind>> {
+			try_err!(inner(), Kind::B, "outer failed");
+			Ok(())
+		}
+
+		let error = outer().unwrap_err();
+		let wrapped: WrappedError = error.into();
+
+		let chain: Vec<_> = wrapped.iter().collect();
+		assert_eq!(chain.len(), 2);
+		assert!(chain[0].to_string().contains("outer failed"));
+		assert!(chain[1].to_string().contains("inner failed"));
+		assert!(chain[0].source().is_some());
+		assert!(chain[1].source().is_none());
+	}
+
+	#[test]
+	fn define_error_generated_from_impls_select_the_mapped_variant() {
+		define_error!(TestKind {
+			InvalidInput,
+			Io
+		}, std::io::Error => Io, std::num::ParseIntError => InvalidInput);
+
+		let io_err = std::io::Error::other("disk on fire");
+		let kind: TestKind = io_err.into();
+		assert_eq!(kind, TestKind::Io);
+
+		let parse_err = "abc".parse::<i32>().unwrap_err();
+		let kind: TestKind = parse_err.into();
+		assert_eq!(kind, TestKind::InvalidInput);
+	}
+
+	#[test]
+	#[cfg(not(feature = "backtrace"))]
+	fn backtrace_is_none_without_the_backtrace_feature() {
+		let error: Error<Kind> = new_err!(Kind::A);
+		assert!(error.backtrace.is_none());
+	}
+	#[test]
+	#[cfg(feature = "backtrace")]
+	fn backtrace_is_some_with_the_backtrace_feature() {
+		let error: Error<Kind> = new_err!(Kind::A);
+		assert!(error.backtrace.is_some());
+	}
+
+	#[test]
+	fn new_with_desc_captures_the_caller_line_via_track_caller() {
+		let error: Error<Kind> = Error::new_with_desc(Kind::A, "boom"); let expected_line = line!();
+		assert_eq!(error.file, file!());
+		assert_eq!(error.line, expected_line);
+	}
+}
+
+
+#[cfg(all(test, not(feature = "std")))]
+mod no_std_tests {
+	use super::*;
+
+	#[derive(Debug, Clone)]
+	enum Kind { A, B }
+
+	#[test]
+	fn new_err_throw_err_and_try_err_compile_and_chain_under_no_std() {
+		fn inner() -> Result<(), Error<Kind>> {
+			throw_err!(Kind::A, "inner failed")
+		}
+		fn outer() -> Result<(), Error<Kind>> {
+			try_err!(inner(), Kind::B, "outer failed");
+			Ok(())
+		}
+
+		let error = outer().unwrap_err();
+		assert_eq!(error.description, "outer failed");
+		assert!(error.backtrace.is_none());
+
+		let sub_error = error.sub_error.as_ref().expect("sub_error should be set");
+		assert_eq!(sub_error.description, "inner failed");
+	}
+}